@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Suffixes tried after a package's dotted directory/file name when probing
+/// `$HOME` for its config files. Mirrors the list previously hard-coded in
+/// `find_pkg_path`.
+pub const DEFAULT_SUFFIXES: &[&str] = &[
+    "rc", ".d", ".conf", ".conf.d", ".toml", ".xml", ".json", ".yml", ".lua",
+];
+
+/// Per-package override. When `path` is set, `find_pkg_path` uses it directly
+/// instead of guessing from the suffix list.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageConfig {
+    pub path: Option<PathBuf>,
+}
+
+/// User-editable configuration loaded from `<repo>/dotfiles.toml`.
+///
+/// Run `dotfiles config` to write a fully-populated default to disk, then
+/// edit it in place.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Suffixes tried after a package's dotted name, in order. Overrides
+    /// [`DEFAULT_SUFFIXES`] when non-empty.
+    pub suffixes: Vec<String>,
+    /// Explicit per-package overrides, keyed by package name.
+    pub packages: HashMap<String, PackageConfig>,
+    /// Named groups of packages, so `store`/`stage` can take a set name in
+    /// place of a list of packages.
+    pub sets: HashMap<String, Vec<String>>,
+    /// When true, `stage` symlinks files from the repo into `$HOME` instead
+    /// of copying them, as if `--link` were passed on every invocation.
+    pub stage_link: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            suffixes: DEFAULT_SUFFIXES.iter().map(|s| s.to_string()).collect(),
+            packages: HashMap::from([(
+                "example".to_string(),
+                PackageConfig {
+                    path: Some(PathBuf::from("/home/user/.config/example")),
+                },
+            )]),
+            sets: HashMap::from([(
+                "example-set".to_string(),
+                vec!["example".to_string()],
+            )]),
+            stage_link: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config at `path`, falling back to [`Config::default`] if it
+    /// does not exist.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Serializes a fully-populated default config and writes it to `path`,
+    /// for use by `dotfiles config`.
+    pub fn dump_default(path: &Path) -> io::Result<()> {
+        let contents =
+            toml::to_string_pretty(&Config::default()).expect("default config should serialize");
+        fs::write(path, contents)
+    }
+}
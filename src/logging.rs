@@ -0,0 +1,46 @@
+/// Output verbosity, derived from the `-v`/`--verbose` and `-q`/`--quiet`
+/// flags. Higher is chattier; negative is quieter than the default.
+///
+/// - `< 0` (`--quiet`): only hard errors are reported.
+/// - `0` (default): warnings and errors.
+/// - `>= 1` (`-v`): per-file copy/delete lines.
+/// - `>= 2` (`-vv`): the full `find_pkg_path` probe sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Verbosity(i8);
+
+impl Verbosity {
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        if quiet {
+            Verbosity(-1)
+        } else {
+            Verbosity(verbose as i8)
+        }
+    }
+
+    pub fn warn(&self, msg: impl std::fmt::Display) {
+        if self.0 >= 0 {
+            eprintln!("warning: {msg}");
+        }
+    }
+
+    pub fn file_op(&self, msg: impl std::fmt::Display) {
+        if self.0 >= 1 {
+            println!("{msg}");
+        }
+    }
+
+    /// For `--dry-run`'s "would do this" lines: these are the whole point of
+    /// the flag, so (unlike `file_op`) they print at the default verbosity
+    /// rather than waiting for `-v`. `--quiet` still suppresses them.
+    pub fn dry_run(&self, msg: impl std::fmt::Display) {
+        if self.0 >= 0 {
+            println!("{msg}");
+        }
+    }
+
+    pub fn probe(&self, msg: impl std::fmt::Display) {
+        if self.0 >= 2 {
+            println!("{msg}");
+        }
+    }
+}
@@ -1,4 +1,11 @@
+mod config;
+mod logging;
+
 use clap::{Parser, Subcommand};
+use config::Config;
+use logging::Verbosity;
+use std::collections::HashSet;
+use std::io::Write as _;
 use std::{fmt, fs, io};
 use std::{
     path::{Path, PathBuf},
@@ -9,6 +16,35 @@ use std::{
 enum Error {
     Clap(clap::error::Error),
     Io(std::io::Error),
+    /// `copy_all` was asked to copy over a destination that already exists
+    /// (an existing directory is accepted only as the destination of a source
+    /// directory, whose contents are merged into it); the caller should have
+    /// run `delete_all` on the conflicting path first.
+    DestExists(PathBuf),
+    /// `copy_all` was asked to copy out of a source that doesn't exist.
+    SrcMissing(PathBuf),
+    /// A filesystem operation failed while copying `src` to `dest`.
+    Copy {
+        src: PathBuf,
+        dest: PathBuf,
+        source: io::Error,
+    },
+    /// A filesystem operation failed while linking `dest` to `src`.
+    Link {
+        src: PathBuf,
+        dest: PathBuf,
+        source: io::Error,
+    },
+    /// A filesystem operation failed while deleting `path`.
+    Delete { path: PathBuf, source: io::Error },
+    /// `path` was expected to live under `base` but didn't.
+    NotNestedUnder { path: PathBuf, base: PathBuf },
+    /// A `[sets]` entry in the config file expands into itself, directly or
+    /// transitively.
+    SetCycle(String),
+    /// One or more packages failed during a `store`/`stage` run; the run
+    /// continues past a failing package and reports the full list at the end.
+    PackagesFailed(Vec<String>),
 }
 
 impl fmt::Display for Error {
@@ -16,6 +52,35 @@ impl fmt::Display for Error {
         match self {
             Error::Clap(e) => write!(f, "{}", e),
             Error::Io(e) => write!(f, "{}", e),
+            Error::DestExists(path) => {
+                write!(f, "{}: destination already exists", path.display())
+            }
+            Error::SrcMissing(path) => write!(f, "{}: no such file or directory", path.display()),
+            Error::Copy { src, dest, source } => write!(
+                f,
+                "failed to copy {} to {}: {source}",
+                src.display(),
+                dest.display()
+            ),
+            Error::Link { src, dest, source } => write!(
+                f,
+                "failed to link {} to {}: {source}",
+                dest.display(),
+                src.display()
+            ),
+            Error::Delete { path, source } => {
+                write!(f, "failed to delete {}: {source}", path.display())
+            }
+            Error::NotNestedUnder { path, base } => write!(
+                f,
+                "{} is not nested under {}",
+                path.display(),
+                base.display()
+            ),
+            Error::PackagesFailed(pkgs) => {
+                write!(f, "failed to process packages: {}", pkgs.join(", "))
+            }
+            Error::SetCycle(name) => write!(f, "set \"{name}\" expands into itself"),
         }
     }
 }
@@ -39,6 +104,22 @@ struct Cli {
     #[arg(short = 'r')]
     repo: Option<PathBuf>,
 
+    /// Print more detail; repeat for more (-v per-file ops, -vv probe paths)
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Suppress warnings; only hard errors are reported
+    #[arg(short = 'q', long = "quiet", global = true)]
+    quiet: bool,
+
+    /// Show what would be deleted/copied without touching the filesystem
+    #[arg(long = "dry-run", global = true)]
+    dry_run: bool,
+
+    /// Don't prompt for confirmation before deleting
+    #[arg(long = "noconfirm", global = true)]
+    noconfirm: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -52,6 +133,15 @@ enum Command {
     Stage {
         #[arg(value_name = "PKGS")]
         pkgs: Vec<String>,
+        /// Symlink files from the repo into $HOME instead of copying them
+        #[arg(long)]
+        link: bool,
+    },
+    /// Write a fully-populated default config to PATH, for reference or as a
+    /// starting point to edit.
+    Config {
+        #[arg(value_name = "PATH", default_value = "dotfiles.toml")]
+        path: PathBuf,
     },
 }
 
@@ -71,120 +161,428 @@ fn default_repo(home: &Path) -> PathBuf {
 
 fn run() -> Result<(), Error> {
     let cli = Cli::try_parse()?;
+    let verbosity = Verbosity::from_flags(cli.verbose, cli.quiet);
+
+    if let Command::Config { path } = &cli.command {
+        Config::dump_default(path)?;
+        return Ok(());
+    }
 
     let home = std::env::var_os("HOME").expect("$HOME should be set");
     let home = Path::new(&home);
     let repo = cli.repo.clone().unwrap_or_else(|| default_repo(home));
+    let config = Config::load(&repo.join("dotfiles.toml"))?;
+
+    let dry_run = cli.dry_run;
+
+    let mut failed = Vec::new();
 
     match cli.command {
         Command::Store { pkgs } => {
+            let pkgs = resolve_pkgs(pkgs, &config)?;
             if pkgs.is_empty() {
-                eprintln!("No packages specified, nothing to do");
+                verbosity.warn("No packages specified, nothing to do");
             }
             for pkg in pkgs {
-                let pkg_path = find_pkg_path(&home, &pkg);
-                if pkg_path.is_none() {
-                    eprintln!("Could not find config files for {pkg}");
-                    continue;
+                let result = (|| -> Result<(), Error> {
+                    let pkg_path = find_pkg_path(&home, &pkg, &config, verbosity, true);
+                    let Some(pkg_path) = pkg_path else {
+                        verbosity.warn(format!("Could not find config files for {pkg}"));
+                        return Ok(());
+                    };
+                    let store_path = repo.join(&pkg);
+                    if !confirm_delete(
+                        &store_path,
+                        &format!("store {pkg} (source: {})", pkg_path.display()),
+                        dry_run,
+                        cli.noconfirm,
+                    )? {
+                        verbosity.warn(format!("Skipping {pkg}"));
+                        return Ok(());
+                    }
+                    // Copy into a fresh temporary location under the store *before*
+                    // touching `store_path`. If `pkg_path` was previously staged
+                    // with `--link`, it (or files nested under it) may be a
+                    // symlink back into `store_path`; deleting `store_path` first
+                    // would destroy the real bytes before `copy_all` ever reads
+                    // them. Copying first means `fs::copy` still follows those
+                    // symlinks to live data, and only once that succeeds do we
+                    // drop the old store contents and swap the new ones in.
+                    let store_tmp = repo.join(format!("{pkg}.dotfiles-tmp"));
+                    delete_all(&store_tmp, verbosity, dry_run)?;
+                    let store_to_pkg = store_relative_path(&pkg_path, &home, &pkg);
+                    let pkg_store_tmp = store_tmp.join(store_to_pkg);
+                    copy_all(&pkg_path, &pkg_store_tmp, verbosity, dry_run)?;
+                    delete_all(&store_path, verbosity, dry_run)?;
+                    if dry_run {
+                        return Ok(());
+                    }
+                    fs::rename(&store_tmp, &store_path).map_err(|source| Error::Copy {
+                        src: store_tmp,
+                        dest: store_path,
+                        source,
+                    })
+                })();
+                if let Err(err) = result {
+                    verbosity.warn(format!("{pkg}: {err}"));
+                    failed.push(pkg);
                 }
-                let pkg_path = pkg_path.unwrap();
-                let store_path = repo.join(pkg);
-                delete_all(&store_path)?;
-                let store_to_pkg = pkg_path.strip_prefix(&home).unwrap();
-                let pkg_store = store_path.join(&store_to_pkg);
-                copy_all(&pkg_path, &pkg_store)?;
             }
         }
-        Command::Stage { pkgs } => {
+        Command::Stage { pkgs, link } => {
+            let link = link || config.stage_link;
+            let pkgs = resolve_pkgs(pkgs, &config)?;
             if pkgs.is_empty() {
-                eprintln!("No packages specified, nothing to do");
+                verbosity.warn("No packages specified, nothing to do");
             }
             for pkg in pkgs {
-                let store_path = repo.join(&pkg);
-                if !store_path.exists() {
-                    eprintln!("No stored config found for {pkg}, skipping");
-                    continue;
+                let result = (|| -> Result<(), Error> {
+                    let store_path = repo.join(&pkg);
+                    if !store_path.exists() {
+                        verbosity.warn(format!("No stored config found for {pkg}, skipping"));
+                        return Ok(());
+                    }
+                    if link {
+                        // link_all reconciles each entry against what's already in
+                        // $HOME, so (unlike copy) staging doesn't wipe the
+                        // destination tree up front.
+                        return link_all(&store_path, &home, verbosity, dry_run, cli.noconfirm);
+                    }
+                    // `consider_overrides: false` here: this call probes the store
+                    // tree, not $HOME, so a `[packages.<pkg>] path` override (an
+                    // absolute path to the live config, not a location inside the
+                    // store) must not be returned as if it were found in the store.
+                    let home_path = find_pkg_path(&store_path, &pkg, &config, verbosity, false)
+                        .map(|p| strip_home(&p, &store_path).map(|rel| home.join(rel)))
+                        .transpose()?
+                        .or_else(|| {
+                            // Already an absolute destination: either `home.join(...)`
+                            // from the normal probe, or a `[packages.<pkg>] path`
+                            // override, which may point outside `$HOME` entirely —
+                            // so unlike the store-probe branch above, this is not
+                            // rejoined under `home`.
+                            find_pkg_path(&home, &pkg, &config, verbosity, true)
+                        });
+                    if let Some(home_path) = home_path {
+                        if !confirm_delete(
+                            &home_path,
+                            &format!("stage {pkg} (source: {})", store_path.display()),
+                            dry_run,
+                            cli.noconfirm,
+                        )? {
+                            verbosity.warn(format!("Skipping {pkg}"));
+                            return Ok(());
+                        }
+                        delete_all(&home_path, verbosity, dry_run)?;
+                    }
+                    copy_all(&store_path, &home, verbosity, dry_run)
+                })();
+                if let Err(err) = result {
+                    verbosity.warn(format!("{pkg}: {err}"));
+                    failed.push(pkg);
                 }
-                let home_to_pkg = find_pkg_path(&store_path, &pkg)
-                    .map(|p| p.strip_prefix(&store_path).unwrap().to_path_buf())
-                    .or_else(|| find_pkg_path(&home, &pkg).map(|p| p.strip_prefix(&home).unwrap().to_path_buf()));
-                if let Some(home_to_pkg) = home_to_pkg {
-                    let home_path = home.join(home_to_pkg);
-                    delete_all(&home_path)?;
-                }
-                copy_all(&store_path, &home)?;
             }
         }
+        Command::Config { .. } => unreachable!("handled above"),
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::PackagesFailed(failed))
+    }
+}
+
+/// Expands any `pkgs` entry that names a `[sets]` alias from the config file
+/// into its member packages, recursively, before `store`/`stage` iterate
+/// them. Tokens that don't match a set are kept as literal packages.
+fn resolve_pkgs(pkgs: Vec<String>, config: &Config) -> Result<Vec<String>, Error> {
+    let mut resolved = Vec::new();
+    let mut stack = HashSet::new();
+    for pkg in pkgs {
+        resolve_pkg(&pkg, config, &mut resolved, &mut stack)?;
     }
+    Ok(resolved)
+}
 
+fn resolve_pkg(
+    token: &str,
+    config: &Config,
+    out: &mut Vec<String>,
+    stack: &mut HashSet<String>,
+) -> Result<(), Error> {
+    let Some(members) = config.sets.get(token) else {
+        out.push(token.to_string());
+        return Ok(());
+    };
+    if !stack.insert(token.to_string()) {
+        return Err(Error::SetCycle(token.to_string()));
+    }
+    for member in members {
+        resolve_pkg(member, config, out, stack)?;
+    }
+    stack.remove(token);
     Ok(())
 }
 
-fn delete_all(path: &Path) -> io::Result<()> {
-    if path.exists() && path.is_dir() {
-        std::fs::remove_dir_all(path)
-    } else if path.exists() && path.is_file() {
-        std::fs::remove_file(path)
-    } else {
-        Ok(())
+/// Where `pkg_path`'s contents land inside the store, relative to the
+/// package's store directory. Mirrors its position under `$HOME` when it has
+/// one, so `stage` can find it there again. A `[packages.<pkg>] path`
+/// override pointing outside `$HOME` — the whole point of that override,
+/// e.g. for config stored in unusual locations — has no such position, so it
+/// falls back to just the resolved path's file name.
+fn store_relative_path(pkg_path: &Path, home: &Path, pkg: &str) -> PathBuf {
+    strip_home(pkg_path, home).unwrap_or_else(|_| {
+        pkg_path
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(pkg))
+    })
+}
+
+/// Strips `base` off the front of `path`, with a contextual error instead of
+/// the panic a bare `strip_prefix(...).unwrap()` would give.
+fn strip_home(path: &Path, base: &Path) -> Result<PathBuf, Error> {
+    path.strip_prefix(base)
+        .map(Path::to_path_buf)
+        .map_err(|_| Error::NotNestedUnder {
+            path: path.to_path_buf(),
+            base: base.to_path_buf(),
+        })
+}
+
+/// Shows the path that would be deleted and prompts for confirmation, unless
+/// `--dry-run` (nothing is deleted, no need to ask) or `--noconfirm` (proceed
+/// without asking) is set. Returns whether the caller should proceed.
+fn confirm_delete(path: &Path, action: &str, dry_run: bool, noconfirm: bool) -> io::Result<bool> {
+    if !path.exists() || dry_run || noconfirm {
+        return Ok(true);
+    }
+    print!("About to delete {} to {action}. Continue? [y/N] ", path.display());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn delete_all(path: &Path, verbosity: Verbosity, dry_run: bool) -> Result<(), Error> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if dry_run {
+        verbosity.dry_run(format!("Would delete {}", path.display()));
+        return Ok(());
     }
+    verbosity.file_op(format!("Deleting {}", path.display()));
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|source| Error::Delete {
+        path: path.to_path_buf(),
+        source,
+    })
 }
 
-fn copy_all(src: &Path, dest: &Path) -> io::Result<()> {
-    if dest.exists() {
-        panic!("should remove dest before copy");
+fn copy_all(src: &Path, dest: &Path, verbosity: Verbosity, dry_run: bool) -> Result<(), Error> {
+    if !dry_run && dest.exists() && !(src.is_dir() && dest.is_dir()) {
+        return Err(Error::DestExists(dest.to_path_buf()));
     }
     if !src.exists() {
-        panic!("src should exist");
+        return Err(Error::SrcMissing(src.to_path_buf()));
     }
+
+    let copy_ctx = |source| Error::Copy {
+        src: src.to_path_buf(),
+        dest: dest.to_path_buf(),
+        source,
+    };
+
+    if src.is_dir() {
+        if !dry_run {
+            fs::create_dir_all(dest).map_err(copy_ctx)?;
+        }
+        for entry in fs::read_dir(src).map_err(copy_ctx)? {
+            let entry = entry.map_err(copy_ctx)?;
+            let src_path = entry.path();
+            let dest_path = dest.join(entry.file_name());
+            copy_all(&src_path, &dest_path, verbosity, dry_run)?;
+        }
+    } else if src.is_file() {
+        if dry_run {
+            verbosity.dry_run(format!("Would copy {} to {}", src.display(), dest.display()));
+        } else {
+            let parent = dest.parent().ok_or_else(|| copy_ctx(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "destination has no parent directory",
+            )))?;
+            fs::create_dir_all(parent).map_err(copy_ctx)?;
+            verbosity.file_op(format!("Copying {} to {}", src.display(), dest.display()));
+            fs::copy(src, dest).map_err(copy_ctx)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// GNU Stow-style alternative to [`copy_all`] for staging: instead of
+/// copying files out of the repo, creates symlinks in `dest` pointing back
+/// at `src`, so edits to a staged config are immediately reflected in the
+/// repo. Unlike `copy_all` it's safe to call repeatedly on an already-linked
+/// tree: existing correct links are left alone, dangling links from a
+/// previous stage are removed, and a real (non-symlink) file at `dest` is
+/// only replaced after confirmation.
+fn link_all(
+    src: &Path,
+    dest: &Path,
+    verbosity: Verbosity,
+    dry_run: bool,
+    noconfirm: bool,
+) -> Result<(), Error> {
+    if !src.exists() {
+        return Err(Error::SrcMissing(src.to_path_buf()));
+    }
+
+    let link_ctx = |source| Error::Link {
+        src: src.to_path_buf(),
+        dest: dest.to_path_buf(),
+        source,
+    };
+
     if src.is_dir() {
-        fs::create_dir_all(dest)?;
-        for entry in fs::read_dir(src)? {
-            let entry = entry?;
+        if !dry_run {
+            fs::create_dir_all(dest).map_err(link_ctx)?;
+        }
+        if dest.is_dir() {
+            for entry in fs::read_dir(dest).map_err(link_ctx)? {
+                let entry = entry.map_err(link_ctx)?;
+                let dest_path = entry.path();
+                let Ok(metadata) = fs::symlink_metadata(&dest_path) else {
+                    continue;
+                };
+                if !metadata.file_type().is_symlink() || dest_path.exists() {
+                    // not a symlink, or a symlink that still resolves: leave it alone
+                    continue;
+                }
+                let Ok(target) = fs::read_link(&dest_path) else {
+                    continue;
+                };
+                // Only clean up links this package's own tree created, i.e. ones
+                // pointing somewhere under `src`. A dangling link that happens to
+                // share a name with something in `src` but belongs to another
+                // package (or isn't ours at all) must not be touched, even when
+                // `dest` is a directory several packages stage into (e.g.
+                // `~/.config`).
+                if !target.starts_with(src) {
+                    continue;
+                }
+                if dry_run {
+                    verbosity.dry_run(format!("Would remove dangling link {}", dest_path.display()));
+                } else {
+                    verbosity.file_op(format!("Removing dangling link {}", dest_path.display()));
+                    fs::remove_file(&dest_path).map_err(link_ctx)?;
+                }
+            }
+        }
+        for entry in fs::read_dir(src).map_err(link_ctx)? {
+            let entry = entry.map_err(link_ctx)?;
             let src_path = entry.path();
             let dest_path = dest.join(entry.file_name());
-            copy_all(&src_path, &dest_path)?;
+            link_all(&src_path, &dest_path, verbosity, dry_run, noconfirm)?;
         }
     } else if src.is_file() {
-        fs::create_dir_all(dest.parent().unwrap())?;
-        println!("Copying {} to {}", src.display(), dest.display());
-        fs::copy(src, dest)?;
+        let is_symlink = fs::symlink_metadata(dest)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink && fs::read_link(dest).map_or(false, |target| target == src) {
+            // already linked to this file: nothing to do
+            return Ok(());
+        }
+        if dest.exists() && !is_symlink {
+            let action = format!("replace it with a link to {}", src.display());
+            if !confirm_delete(dest, &action, dry_run, noconfirm)? {
+                verbosity.warn(format!("Skipping {}, not a link", dest.display()));
+                return Ok(());
+            }
+        }
+        if dry_run {
+            verbosity.dry_run(format!("Would link {} -> {}", dest.display(), src.display()));
+            return Ok(());
+        }
+        if dest.exists() || is_symlink {
+            fs::remove_file(dest).map_err(link_ctx)?;
+        }
+        fs::create_dir_all(dest.parent().unwrap_or(Path::new("."))).map_err(link_ctx)?;
+        verbosity.file_op(format!("Linking {} -> {}", dest.display(), src.display()));
+        std::os::unix::fs::symlink(src, dest).map_err(link_ctx)?;
     }
 
     Ok(())
 }
 
-fn find_pkg_path(home: &Path, pkg: &str) -> Option<PathBuf> {
+fn find_pkg_path(
+    home: &Path,
+    pkg: &str,
+    config: &Config,
+    verbosity: Verbosity,
+    consider_overrides: bool,
+) -> Option<PathBuf> {
     // check in order for:
+    //  - an explicit `[packages.pkg] path` in the config file (only when probing
+    //    the real $HOME; see `consider_overrides`)
     //  - ~/.pkg
     //  - ~/.pkg[suffix]
     //  - ~/.config/pkg
     //  - ~/.config/pkg[suffix]
-    // [suffix] being one of:
-    //   rc, .d, .conf, .conf.d, .toml, .xml, .json, .yml, .lua
+    // [suffix] being one of the configured `suffixes` (config.rs::DEFAULT_SUFFIXES
+    // unless overridden)
     // it stops at the first occurence found
 
-    fn check(path: PathBuf) -> Option<PathBuf> {
-        if path.exists() { Some(path) } else { None }
+    fn check(path: PathBuf, verbosity: Verbosity) -> Option<PathBuf> {
+        let exists = path.exists();
+        verbosity.probe(format!(
+            "probing {}: {}",
+            path.display(),
+            if exists { "found" } else { "not found" }
+        ));
+        if exists { Some(path) } else { None }
     }
 
-    let suffixes = &[
-        "rc", ".d", ".conf", ".conf.d", ".toml", ".xml", ".json", ".yml", ".lua",
-    ];
+    if consider_overrides {
+        if let Some(path) = config
+            .packages
+            .get(pkg)
+            .and_then(|pkg_config| pkg_config.path.clone())
+        {
+            return check(path, verbosity);
+        }
+    }
+
+    let default_suffixes: Vec<String>;
+    let suffixes = if config.suffixes.is_empty() {
+        default_suffixes = crate::config::DEFAULT_SUFFIXES
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        &default_suffixes
+    } else {
+        &config.suffixes
+    };
     let dotpkg = format!(".{pkg}");
-    if let Some(path) = check(home.join(&dotpkg)) {
+    if let Some(path) = check(home.join(&dotpkg), verbosity) {
         return Some(path);
     }
     for s in suffixes.iter() {
-        if let Some(path) = check(home.join(format!("{dotpkg}{s}"))) {
+        if let Some(path) = check(home.join(format!("{dotpkg}{s}")), verbosity) {
             return Some(path);
         }
     }
-    if let Some(path) = check(home.join(".config").join(pkg)) {
+    if let Some(path) = check(home.join(".config").join(pkg), verbosity) {
         return Some(path);
     }
     for s in suffixes.iter() {
-        if let Some(path) = check(home.join(".config").join(format!("{pkg}{s}"))) {
+        if let Some(path) = check(home.join(".config").join(format!("{pkg}{s}")), verbosity) {
             return Some(path);
         }
     }